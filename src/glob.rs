@@ -0,0 +1,85 @@
+/// Matches `subject` against a glob `pattern` supporting `*` (any run, including
+/// empty), `?` (single byte), and `[...]` character classes (optionally negated
+/// with a leading `^`, and supporting `a-z` style ranges).
+///
+/// Uses the standard two-pointer backtracking algorithm: advance the pattern
+/// and subject cursors together; on `*` remember the position and retry from
+/// there on a later mismatch. Runs in O(pattern * subject) worst case with
+/// O(1) extra state.
+pub fn glob_match(pattern: &[u8], subject: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    let mut star: Option<usize> = None;
+    let mut star_match = 0;
+
+    while s < subject.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some(p);
+            star_match = s;
+            p += 1;
+        } else if p < pattern.len() && matches_one(pattern, &mut p, subject[s]) {
+            s += 1;
+        } else if let Some(star_pos) = star {
+            p = star_pos + 1;
+            star_match += 1;
+            s = star_match;
+        } else {
+            return false;
+        }
+    }
+
+    while p < pattern.len() && pattern[p] == b'*' {
+        p += 1;
+    }
+
+    p == pattern.len()
+}
+
+/// Attempts to match the single pattern atom at `*p` against `c`, advancing
+/// `*p` past the atom on a match. `*p` is left unspecified on a mismatch,
+/// since callers always reset it from the last `*` on failure.
+fn matches_one(pattern: &[u8], p: &mut usize, c: u8) -> bool {
+    match pattern[*p] {
+        b'?' => {
+            *p += 1;
+            true
+        }
+        b'[' => {
+            let after_bracket = *p + 1;
+            let negate = pattern.get(after_bracket) == Some(&b'^');
+            let class_start = if negate {
+                after_bracket + 1
+            } else {
+                after_bracket
+            };
+            let Some(end) = pattern[class_start..].iter().position(|&b| b == b']') else {
+                *p += 1;
+                return pattern[*p - 1] == c;
+            };
+            let end = class_start + end;
+            let class = &pattern[class_start..end];
+
+            let mut found = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == b'-' {
+                    if class[i] <= c && c <= class[i + 2] {
+                        found = true;
+                    }
+                    i += 3;
+                } else {
+                    if class[i] == c {
+                        found = true;
+                    }
+                    i += 1;
+                }
+            }
+
+            *p = end + 1;
+            found != negate
+        }
+        literal => {
+            *p += 1;
+            literal == c
+        }
+    }
+}
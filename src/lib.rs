@@ -0,0 +1,88 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+mod glob;
+mod resp;
+mod snapshot;
+mod transport;
+
+pub use glob::glob_match;
+pub use resp::{RespCodec, RespError, RespValue};
+pub use snapshot::{
+    load as load_snapshot, save as save_snapshot, Entry as SnapshotEntry, SnapshotError,
+};
+pub use transport::{ConnectionCodec, ConnectionError, EncryptedCodec};
+
+#[derive(Parser, Debug)]
+#[command(name = "redis-server")]
+pub struct Cli {
+    #[arg(long, default_value_t = 6379)]
+    pub port: u16,
+
+    #[arg(long)]
+    pub replicaof: Option<String>,
+
+    /// Encrypt client connections with ChaCha20-Poly1305.
+    #[arg(long)]
+    pub encrypt: bool,
+
+    /// 32-byte encryption key, hex-encoded (64 hex characters). Required when `--encrypt` is set.
+    #[arg(long, env = "REDIS_ENCRYPTION_KEY")]
+    pub encryption_key: Option<String>,
+
+    /// Also accept RESP-over-WebSocket connections on `--ws-port`.
+    #[arg(long)]
+    pub ws: bool,
+
+    #[arg(long, default_value_t = 6390)]
+    pub ws_port: u16,
+
+    /// Directory the snapshot file is loaded from and saved to.
+    #[arg(long, default_value = ".")]
+    pub dir: String,
+
+    /// Snapshot file name, relative to `--dir`.
+    #[arg(long, default_value = "dump.rdb")]
+    pub dbfilename: String,
+
+    /// Disable the SAVE/BGSAVE commands, so the dataset is never written to disk.
+    #[arg(long)]
+    pub nosave: bool,
+}
+
+pub struct ServerConfig {
+    pub port: u16,
+    pub is_slave: bool,
+    pub encryption_key: Option<[u8; 32]>,
+    pub ws_port: Option<u16>,
+    pub rdb_path: PathBuf,
+    pub save_enabled: bool,
+}
+
+impl ServerConfig {
+    pub fn new(cli: &Cli) -> Self {
+        let encryption_key = if cli.encrypt {
+            Some(parse_encryption_key(cli.encryption_key.as_deref().expect(
+                "--encrypt requires --encryption-key or REDIS_ENCRYPTION_KEY to be set",
+            )))
+        } else {
+            None
+        };
+
+        Self {
+            port: cli.port,
+            is_slave: cli.replicaof.is_some(),
+            encryption_key,
+            ws_port: cli.ws.then_some(cli.ws_port),
+            rdb_path: PathBuf::from(&cli.dir).join(&cli.dbfilename),
+            save_enabled: !cli.nosave,
+        }
+    }
+}
+
+fn parse_encryption_key(hex_key: &str) -> [u8; 32] {
+    let bytes = hex::decode(hex_key).expect("encryption key must be valid hex");
+    bytes
+        .try_into()
+        .expect("encryption key must be exactly 32 bytes (64 hex characters)")
+}
@@ -1,20 +1,65 @@
-use bytes::{Bytes, BytesMut};
+use bytes::Bytes;
 use clap::Parser;
+use futures::{SinkExt, StreamExt};
+use rand::seq::IteratorRandom;
 use redis_starter_rust::*;
 use std::{
     collections::HashMap,
     error::Error,
-    time::{Duration, Instant},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
-    sync::oneshot,
+    sync::{mpsc, oneshot},
 };
+use tokio_tungstenite::tungstenite::Message;
+use tokio_util::codec::Framed;
 
 enum DbOperation {
     Get(String, oneshot::Sender<Option<(Bytes, Option<Instant>)>>),
-    Set(String, Bytes, Option<Instant>, oneshot::Sender<()>),
+    Set {
+        key: String,
+        value: Bytes,
+        expiry: Option<Instant>,
+        condition: SetCondition,
+        want_old: bool,
+        response: oneshot::Sender<SetOutcome>,
+    },
+    Keys(String, oneshot::Sender<Vec<String>>),
+    /// cursor, pattern, count -> (next cursor, matching keys in this page)
+    Scan(usize, String, usize, oneshot::Sender<(usize, Vec<String>)>),
+    DeleteMatching(String, oneshot::Sender<u64>),
+    /// sample size -> (keys sampled, keys evicted)
+    SweepExpired(usize, oneshot::Sender<(usize, usize)>),
+    /// Writes a snapshot synchronously, blocking the actor until it's done.
+    Save(PathBuf, oneshot::Sender<Result<(), SnapshotError>>),
+    /// Clones a view of the keyspace and writes it on a blocking task, so the
+    /// actor keeps serving other operations while the snapshot is written.
+    BgSave(PathBuf, oneshot::Sender<()>),
+}
+
+enum SetCondition {
+    Always,
+    IfNotExists,
+    IfExists,
+}
+
+enum SetOutcome {
+    ConditionNotMet,
+    Set(Option<Bytes>),
+}
+
+/// Identifies a single connection's subscription to the pub/sub broker.
+type ConnId = u64;
+
+static NEXT_CONN_ID: AtomicU64 = AtomicU64::new(0);
+
+enum BrokerOperation {
+    Subscribe(String, ConnId, mpsc::Sender<RespValue>),
+    Unsubscribe(String, ConnId),
+    Publish(String, Bytes, oneshot::Sender<u64>),
 }
 
 #[tokio::main]
@@ -31,177 +76,653 @@ async fn main() -> Result<(), Box<dyn Error>> {
         println!("Running as master");
     }
 
+    let initial_entries = if config.rdb_path.exists() {
+        match load_snapshot(&config.rdb_path) {
+            Ok(entries) => {
+                println!(
+                    "Loaded {} key(s) from {}",
+                    entries.len(),
+                    config.rdb_path.display()
+                );
+                entries
+            }
+            Err(e) => {
+                eprintln!(
+                    "Failed to load snapshot from {}: {}",
+                    config.rdb_path.display(),
+                    e
+                );
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
     let (db_sender, db_receiver) = async_channel::unbounded();
+    let (broker_sender, broker_receiver) = async_channel::unbounded();
 
-    tokio::spawn(run_database(db_receiver));
+    tokio::spawn(run_database(db_receiver, initial_entries));
+    tokio::spawn(run_broker(broker_receiver));
+    tokio::spawn(run_expiration_sweeper(db_sender.clone()));
+
+    if let Some(ws_port) = config.ws_port {
+        let ws_listener = TcpListener::bind(format!("127.0.0.1:{}", ws_port)).await?;
+        println!(
+            "Listening for WebSocket connections on 127.0.0.1:{}",
+            ws_port
+        );
+        let db_sender = db_sender.clone();
+        let rdb_path = config.rdb_path.clone();
+        let save_enabled = config.save_enabled;
+        tokio::spawn(run_ws_listener(
+            ws_listener,
+            db_sender,
+            rdb_path,
+            save_enabled,
+        ));
+    }
 
     loop {
         let (socket, _) = listener.accept().await?;
         let db_sender = db_sender.clone();
+        let broker_sender = broker_sender.clone();
+        let encryption_key = config.encryption_key;
+        let rdb_path = config.rdb_path.clone();
+        let save_enabled = config.save_enabled;
         tokio::spawn(async move {
-            if let Err(e) = process(socket, db_sender).await {
+            if let Err(e) = process(
+                socket,
+                db_sender,
+                broker_sender,
+                encryption_key,
+                rdb_path,
+                save_enabled,
+            )
+            .await
+            {
                 eprintln!("Error processing connection: {}", e);
             }
         });
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn process(
-    mut socket: TcpStream,
+    socket: TcpStream,
     db_sender: async_channel::Sender<DbOperation>,
+    broker_sender: async_channel::Sender<BrokerOperation>,
+    encryption_key: Option<[u8; 32]>,
+    rdb_path: PathBuf,
+    save_enabled: bool,
 ) -> Result<(), Box<dyn Error>> {
-    let mut buffer = BytesMut::with_capacity(1024);
+    let conn_id = NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed);
+    let codec = match encryption_key {
+        Some(key) => ConnectionCodec::Encrypted(EncryptedCodec::new(key)),
+        None => ConnectionCodec::Plain(RespCodec),
+    };
+    let (sink, stream) = Framed::new(socket, codec).split();
+    let (push_sender, push_receiver) = mpsc::channel::<RespValue>(16);
+    let mut subscriptions: Vec<String> = Vec::new();
+
+    let result = handle_connection(
+        sink,
+        stream,
+        push_sender,
+        push_receiver,
+        &db_sender,
+        &broker_sender,
+        conn_id,
+        &mut subscriptions,
+        &rdb_path,
+        save_enabled,
+    )
+    .await;
 
+    for channel in subscriptions {
+        let _ = broker_sender
+            .send(BrokerOperation::Unsubscribe(channel, conn_id))
+            .await;
+    }
+
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_connection(
+    mut sink: futures::stream::SplitSink<Framed<TcpStream, ConnectionCodec>, RespValue>,
+    mut stream: futures::stream::SplitStream<Framed<TcpStream, ConnectionCodec>>,
+    push_sender: mpsc::Sender<RespValue>,
+    mut push_receiver: mpsc::Receiver<RespValue>,
+    db_sender: &async_channel::Sender<DbOperation>,
+    broker_sender: &async_channel::Sender<BrokerOperation>,
+    conn_id: ConnId,
+    subscriptions: &mut Vec<String>,
+    rdb_path: &Path,
+    save_enabled: bool,
+) -> Result<(), Box<dyn Error>> {
     loop {
-        let bytes_read = socket.read_buf(&mut buffer).await?;
-        if bytes_read == 0 {
-            return Ok(());
-        }
+        let values = tokio::select! {
+            push = push_receiver.recv() => {
+                let Some(message) = push else { continue };
+                sink.send(message).await?;
+                continue;
+            }
+            frame = stream.next() => {
+                match frame {
+                    None => return Ok(()),
+                    Some(Ok(RespValue::Array(values))) => values,
+                    Some(Ok(_)) => {
+                        sink.send(RespValue::Error("Invalid request format".to_string()))
+                            .await?;
+                        continue;
+                    }
+                    Some(Err(e)) => return Err(e.into()),
+                }
+            }
+        };
 
-        let request = Bytes::from(buffer.split_to(bytes_read));
-        match RespValue::try_from(request) {
-            Ok(RespValue::Array(values)) => {
-                if let Some(RespValue::BulkString(Some(command))) = values.first() {
-                    let command_str = String::from_utf8_lossy(command);
-                    match command_str.to_ascii_lowercase().as_str() {
-                        "ping" => {
-                            let response =
-                                if let Some(RespValue::BulkString(Some(arg))) = values.get(1) {
-                                    RespValue::BulkString(Some(arg.clone()))
-                                } else {
-                                    RespValue::SimpleString("PONG".to_string())
-                                };
-                            let response_bytes = Bytes::from(response);
-                            socket.write_all(&response_bytes).await?;
-                        }
-                        "echo" => {
-                            if let Some(RespValue::BulkString(Some(arg))) = values.get(1) {
-                                let response = RespValue::BulkString(Some(arg.clone()));
-                                let response_bytes = Bytes::from(response);
-                                socket.write_all(&response_bytes).await?;
-                            } else {
-                                let error =
-                                    RespValue::Error("Invalid ECHO command format".to_string());
-                                let error_bytes = Bytes::from(error);
-                                socket.write_all(&error_bytes).await?;
+        let Some(RespValue::BulkString(Some(command))) = values.first() else {
+            sink.send(RespValue::Error("Invalid command format".to_string()))
+                .await?;
+            continue;
+        };
+        let command_str = String::from_utf8_lossy(command).to_ascii_lowercase();
+
+        match command_str.as_str() {
+            "subscribe" => {
+                for arg in &values[1..] {
+                    let RespValue::BulkString(Some(channel)) = arg else {
+                        continue;
+                    };
+                    let channel = String::from_utf8_lossy(channel).to_string();
+                    broker_sender
+                        .send(BrokerOperation::Subscribe(
+                            channel.clone(),
+                            conn_id,
+                            push_sender.clone(),
+                        ))
+                        .await?;
+                    subscriptions.push(channel.clone());
+                    let confirmation = RespValue::Push(vec![
+                        RespValue::BulkString(Some(Bytes::from_static(b"subscribe"))),
+                        RespValue::BulkString(Some(Bytes::from(channel))),
+                        RespValue::Integer(subscriptions.len() as i64),
+                    ]);
+                    sink.send(confirmation).await?;
+                }
+            }
+            "unsubscribe" => {
+                let channels: Vec<String> = if values.len() > 1 {
+                    values[1..]
+                        .iter()
+                        .filter_map(|v| match v {
+                            RespValue::BulkString(Some(channel)) => {
+                                Some(String::from_utf8_lossy(channel).to_string())
                             }
-                        }
-                        "get" => {
-                            if let Some(RespValue::BulkString(Some(key))) = values.get(1) {
-                                let key_str = String::from_utf8_lossy(key).to_string();
-                                let (response_sender, response_receiver) = oneshot::channel();
-                                db_sender
-                                    .send(DbOperation::Get(key_str, response_sender))
-                                    .await?;
-                                let response = match response_receiver.await? {
-                                    Some((value, expiry)) => {
-                                        if let Some(exp) = expiry {
-                                            if Instant::now() > exp {
-                                                RespValue::BulkString(None)
-                                            } else {
-                                                RespValue::BulkString(Some(value))
-                                            }
-                                        } else {
-                                            RespValue::BulkString(Some(value))
-                                        }
-                                    }
-                                    None => RespValue::BulkString(None),
-                                };
-                                let response_bytes = Bytes::from(response);
-                                socket.write_all(&response_bytes).await?;
+                            _ => None,
+                        })
+                        .collect()
+                } else {
+                    subscriptions.clone()
+                };
+                for channel in channels {
+                    broker_sender
+                        .send(BrokerOperation::Unsubscribe(channel.clone(), conn_id))
+                        .await?;
+                    subscriptions.retain(|c| c != &channel);
+                    let confirmation = RespValue::Push(vec![
+                        RespValue::BulkString(Some(Bytes::from_static(b"unsubscribe"))),
+                        RespValue::BulkString(Some(Bytes::from(channel))),
+                        RespValue::Integer(subscriptions.len() as i64),
+                    ]);
+                    sink.send(confirmation).await?;
+                }
+            }
+            "publish" => {
+                let response = if let (
+                    Some(RespValue::BulkString(Some(channel))),
+                    Some(RespValue::BulkString(Some(message))),
+                ) = (values.get(1), values.get(2))
+                {
+                    let channel = String::from_utf8_lossy(channel).to_string();
+                    let (response_sender, response_receiver) = oneshot::channel();
+                    broker_sender
+                        .send(BrokerOperation::Publish(
+                            channel,
+                            message.clone(),
+                            response_sender,
+                        ))
+                        .await?;
+                    RespValue::Integer(response_receiver.await? as i64)
+                } else {
+                    RespValue::Error("Invalid PUBLISH command format".to_string())
+                };
+                sink.send(response).await?;
+            }
+            _ => {
+                let response = handle_command(&values, db_sender, rdb_path, save_enabled).await;
+                sink.send(response).await?;
+            }
+        }
+    }
+}
+
+/// Executes a single command against the shared database and returns the
+/// reply to send back, independent of whatever transport (TCP, WebSocket)
+/// carried the request in.
+async fn handle_command(
+    values: &[RespValue],
+    db_sender: &async_channel::Sender<DbOperation>,
+    rdb_path: &Path,
+    save_enabled: bool,
+) -> RespValue {
+    let Some(RespValue::BulkString(Some(command))) = values.first() else {
+        return RespValue::Error("Invalid command format".to_string());
+    };
+    let command_str = String::from_utf8_lossy(command).to_ascii_lowercase();
+
+    match command_str.as_str() {
+        "ping" => {
+            if let Some(RespValue::BulkString(Some(arg))) = values.get(1) {
+                RespValue::BulkString(Some(arg.clone()))
+            } else {
+                RespValue::SimpleString("PONG".to_string())
+            }
+        }
+        "echo" => {
+            if let Some(RespValue::BulkString(Some(arg))) = values.get(1) {
+                RespValue::BulkString(Some(arg.clone()))
+            } else {
+                RespValue::Error("Invalid ECHO command format".to_string())
+            }
+        }
+        "get" => {
+            if let Some(RespValue::BulkString(Some(key))) = values.get(1) {
+                let key_str = String::from_utf8_lossy(key).to_string();
+                let (response_sender, response_receiver) = oneshot::channel();
+                if db_sender
+                    .send(DbOperation::Get(key_str, response_sender))
+                    .await
+                    .is_err()
+                {
+                    return RespValue::Error("Database unavailable".to_string());
+                }
+                match response_receiver.await {
+                    Ok(Some((value, expiry))) => {
+                        if let Some(exp) = expiry {
+                            if Instant::now() > exp {
+                                RespValue::BulkString(None)
                             } else {
-                                let error =
-                                    RespValue::Error("Invalid GET command format".to_string());
-                                let error_bytes = Bytes::from(error);
-                                socket.write_all(&error_bytes).await?;
+                                RespValue::BulkString(Some(value))
                             }
+                        } else {
+                            RespValue::BulkString(Some(value))
                         }
+                    }
+                    Ok(None) => RespValue::BulkString(None),
+                    Err(_) => RespValue::Error("Database unavailable".to_string()),
+                }
+            } else {
+                RespValue::Error("Invalid GET command format".to_string())
+            }
+        }
 
-                        "set" => {
-                            match (values.get(1), values.get(2), values.get(3), values.get(4)) {
-                                (
-                                    Some(RespValue::BulkString(Some(key))),
-                                    Some(RespValue::BulkString(Some(value))),
-                                    Some(RespValue::BulkString(Some(px_bytes))),
-                                    Some(RespValue::BulkString(Some(ms))),
-                                ) if px_bytes.to_ascii_lowercase() == b"px" => {
-                                    let key_str = String::from_utf8_lossy(key).to_string();
-                                    let expiry = String::from_utf8_lossy(ms)
-                                        .parse::<u64>()
-                                        .map(|ms| Instant::now() + Duration::from_millis(ms))
-                                        .ok();
-                                    let (response_sender, response_receiver) = oneshot::channel();
-                                    db_sender
-                                        .send(DbOperation::Set(
-                                            key_str,
-                                            value.clone(),
-                                            expiry,
-                                            response_sender,
-                                        ))
-                                        .await?;
-                                    response_receiver.await?;
-                                    let response = RespValue::SimpleString("OK".to_string());
-                                    let response_bytes = Bytes::from(response);
-                                    socket.write_all(&response_bytes).await?;
-                                }
-                                (
-                                    Some(RespValue::BulkString(Some(key))),
-                                    Some(RespValue::BulkString(Some(value))),
-                                    None,
-                                    None,
-                                ) => {
-                                    let key_str = String::from_utf8_lossy(key).to_string();
-                                    let (response_sender, response_receiver) =
-                                        tokio::sync::oneshot::channel();
-                                    db_sender
-                                        .send(DbOperation::Set(
-                                            key_str,
-                                            value.clone(),
-                                            None,
-                                            response_sender,
-                                        ))
-                                        .await?;
-                                    response_receiver.await?;
-                                    let response = RespValue::SimpleString("OK".to_string());
-                                    let response_bytes = Bytes::from(response);
-                                    socket.write_all(&response_bytes).await?;
-                                }
-                                _ => {
-                                    let error =
-                                        RespValue::Error("Invalid SET command format".to_string());
-                                    let error_bytes = Bytes::from(error);
-                                    socket.write_all(&error_bytes).await?;
-                                }
-                            }
-                        }
+        "set" => {
+            let (key, value) = match (values.get(1), values.get(2)) {
+                (
+                    Some(RespValue::BulkString(Some(key))),
+                    Some(RespValue::BulkString(Some(value))),
+                ) => (key.clone(), value.clone()),
+                _ => return RespValue::Error("Invalid SET command format".to_string()),
+            };
 
-                        _ => {
-                            let error = RespValue::Error("Unknown command".to_string());
-                            let error_bytes = Bytes::from(error);
-                            socket.write_all(&error_bytes).await?;
-                        }
+            let mut expiry_after: Option<Duration> = None;
+            let mut condition = SetCondition::Always;
+            let mut want_old = false;
+
+            let mut options = values[3..].iter();
+            while let Some(option) = options.next() {
+                let RespValue::BulkString(Some(option)) = option else {
+                    return RespValue::Error("Invalid SET command format".to_string());
+                };
+                match option.to_ascii_lowercase().as_slice() {
+                    b"ex" => {
+                        let Some(RespValue::BulkString(Some(secs))) = options.next() else {
+                            return RespValue::Error("Invalid SET EX format".to_string());
+                        };
+                        let Ok(secs) = String::from_utf8_lossy(secs).parse::<u64>() else {
+                            return RespValue::Error("Invalid SET EX value".to_string());
+                        };
+                        expiry_after = Some(Duration::from_secs(secs));
                     }
-                } else {
-                    let error = RespValue::Error("Invalid command format".to_string());
-                    let error_bytes = Bytes::from(error);
-                    socket.write_all(&error_bytes).await?;
+                    b"px" => {
+                        let Some(RespValue::BulkString(Some(ms))) = options.next() else {
+                            return RespValue::Error("Invalid SET PX format".to_string());
+                        };
+                        let Ok(ms) = String::from_utf8_lossy(ms).parse::<u64>() else {
+                            return RespValue::Error("Invalid SET PX value".to_string());
+                        };
+                        expiry_after = Some(Duration::from_millis(ms));
+                    }
+                    b"nx" => condition = SetCondition::IfNotExists,
+                    b"xx" => condition = SetCondition::IfExists,
+                    b"get" => want_old = true,
+                    _ => return RespValue::Error("Invalid SET command format".to_string()),
                 }
             }
-            Ok(_) => {
-                let error = RespValue::Error("Invalid request format".to_string());
-                let error_bytes = Bytes::from(error);
-                socket.write_all(&error_bytes).await?;
+
+            let key_str = String::from_utf8_lossy(&key).to_string();
+            let expiry = expiry_after.map(|duration| Instant::now() + duration);
+            let (response_sender, response_receiver) = oneshot::channel();
+            if db_sender
+                .send(DbOperation::Set {
+                    key: key_str,
+                    value,
+                    expiry,
+                    condition,
+                    want_old,
+                    response: response_sender,
+                })
+                .await
+                .is_err()
+            {
+                return RespValue::Error("Database unavailable".to_string());
             }
+
+            match response_receiver.await {
+                Ok(SetOutcome::ConditionNotMet) => RespValue::BulkString(None),
+                Ok(SetOutcome::Set(old)) => {
+                    if want_old {
+                        RespValue::BulkString(old)
+                    } else {
+                        RespValue::SimpleString("OK".to_string())
+                    }
+                }
+                Err(_) => RespValue::Error("Database unavailable".to_string()),
+            }
+        }
+
+        "keys" => {
+            if let Some(RespValue::BulkString(Some(pattern))) = values.get(1) {
+                let pattern = String::from_utf8_lossy(pattern).to_string();
+                let (response_sender, response_receiver) = oneshot::channel();
+                if db_sender
+                    .send(DbOperation::Keys(pattern, response_sender))
+                    .await
+                    .is_err()
+                {
+                    return RespValue::Error("Database unavailable".to_string());
+                }
+                match response_receiver.await {
+                    Ok(keys) => RespValue::Array(
+                        keys.into_iter()
+                            .map(|key| RespValue::BulkString(Some(Bytes::from(key))))
+                            .collect(),
+                    ),
+                    Err(_) => RespValue::Error("Database unavailable".to_string()),
+                }
+            } else {
+                RespValue::Error("Invalid KEYS command format".to_string())
+            }
+        }
+
+        "scan" => {
+            let Some(RespValue::BulkString(Some(cursor_bytes))) = values.get(1) else {
+                return RespValue::Error("Invalid SCAN command format".to_string());
+            };
+            let Ok(cursor) = String::from_utf8_lossy(cursor_bytes).parse::<usize>() else {
+                return RespValue::Error("Invalid SCAN cursor".to_string());
+            };
+
+            let mut pattern = "*".to_string();
+            let mut count = 10usize;
+            let mut args = values[2..].iter();
+            while let Some(RespValue::BulkString(Some(option))) = args.next() {
+                match option.to_ascii_lowercase().as_slice() {
+                    b"match" => {
+                        let Some(RespValue::BulkString(Some(p))) = args.next() else {
+                            return RespValue::Error("Invalid SCAN MATCH format".to_string());
+                        };
+                        pattern = String::from_utf8_lossy(p).to_string();
+                    }
+                    b"count" => {
+                        let Some(RespValue::BulkString(Some(c))) = args.next() else {
+                            return RespValue::Error("Invalid SCAN COUNT format".to_string());
+                        };
+                        let Ok(parsed) = String::from_utf8_lossy(c).parse::<usize>() else {
+                            return RespValue::Error("Invalid SCAN COUNT value".to_string());
+                        };
+                        count = parsed;
+                    }
+                    _ => return RespValue::Error("Invalid SCAN command format".to_string()),
+                }
+            }
+
+            let (response_sender, response_receiver) = oneshot::channel();
+            if db_sender
+                .send(DbOperation::Scan(cursor, pattern, count, response_sender))
+                .await
+                .is_err()
+            {
+                return RespValue::Error("Database unavailable".to_string());
+            }
+            match response_receiver.await {
+                Ok((next_cursor, keys)) => RespValue::Array(vec![
+                    RespValue::BulkString(Some(Bytes::from(next_cursor.to_string()))),
+                    RespValue::Array(
+                        keys.into_iter()
+                            .map(|key| RespValue::BulkString(Some(Bytes::from(key))))
+                            .collect(),
+                    ),
+                ]),
+                Err(_) => RespValue::Error("Database unavailable".to_string()),
+            }
+        }
+
+        "delpattern" => {
+            if let Some(RespValue::BulkString(Some(pattern))) = values.get(1) {
+                let pattern = String::from_utf8_lossy(pattern).to_string();
+                let (response_sender, response_receiver) = oneshot::channel();
+                if db_sender
+                    .send(DbOperation::DeleteMatching(pattern, response_sender))
+                    .await
+                    .is_err()
+                {
+                    return RespValue::Error("Database unavailable".to_string());
+                }
+                match response_receiver.await {
+                    Ok(deleted) => RespValue::Integer(deleted as i64),
+                    Err(_) => RespValue::Error("Database unavailable".to_string()),
+                }
+            } else {
+                RespValue::Error("Invalid DELPATTERN command format".to_string())
+            }
+        }
+
+        "save" => {
+            if !save_enabled {
+                return RespValue::Error("ERR saving is disabled (--nosave)".to_string());
+            }
+            let (response_sender, response_receiver) = oneshot::channel();
+            if db_sender
+                .send(DbOperation::Save(rdb_path.to_path_buf(), response_sender))
+                .await
+                .is_err()
+            {
+                return RespValue::Error("Database unavailable".to_string());
+            }
+            match response_receiver.await {
+                Ok(Ok(())) => RespValue::SimpleString("OK".to_string()),
+                Ok(Err(e)) => RespValue::Error(format!("ERR {}", e)),
+                Err(_) => RespValue::Error("Database unavailable".to_string()),
+            }
+        }
+
+        "bgsave" => {
+            if !save_enabled {
+                return RespValue::Error("ERR saving is disabled (--nosave)".to_string());
+            }
+            let (response_sender, response_receiver) = oneshot::channel();
+            if db_sender
+                .send(DbOperation::BgSave(rdb_path.to_path_buf(), response_sender))
+                .await
+                .is_err()
+            {
+                return RespValue::Error("Database unavailable".to_string());
+            }
+            match response_receiver.await {
+                Ok(()) => RespValue::SimpleString("Background saving started".to_string()),
+                Err(_) => RespValue::Error("Database unavailable".to_string()),
+            }
+        }
+
+        _ => RespValue::Error("Unknown command".to_string()),
+    }
+}
+async fn run_ws_listener(
+    listener: TcpListener,
+    db_sender: async_channel::Sender<DbOperation>,
+    rdb_path: PathBuf,
+    save_enabled: bool,
+) {
+    loop {
+        let (socket, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
             Err(e) => {
-                let error = RespValue::Error(format!("Parse error: {}", e));
-                let error_bytes = Bytes::from(error);
-                socket.write_all(&error_bytes).await?;
+                eprintln!("Error accepting WebSocket connection: {}", e);
+                continue;
+            }
+        };
+        let db_sender = db_sender.clone();
+        let rdb_path = rdb_path.clone();
+        tokio::spawn(async move {
+            if let Err(e) = process_ws(socket, db_sender, rdb_path, save_enabled).await {
+                eprintln!("Error processing WebSocket connection: {}", e);
+            }
+        });
+    }
+}
+
+/// Mirrors `process`, but carries RESP frames inside binary WebSocket messages
+/// instead of a raw TCP byte stream, reusing `handle_command` for semantics.
+async fn process_ws(
+    socket: TcpStream,
+    db_sender: async_channel::Sender<DbOperation>,
+    rdb_path: PathBuf,
+    save_enabled: bool,
+) -> Result<(), Box<dyn Error>> {
+    let ws_stream = tokio_tungstenite::accept_async(socket).await?;
+    let (mut sink, mut stream) = ws_stream.split();
+
+    while let Some(message) = stream.next().await {
+        let Message::Binary(data) = message? else {
+            continue;
+        };
+
+        let mut pos = 0;
+        let response = match RespValue::parse(&data, &mut pos) {
+            Ok(Some(RespValue::Array(values))) => {
+                handle_command(&values, &db_sender, &rdb_path, save_enabled).await
+            }
+            Ok(Some(_)) => RespValue::Error("Invalid request format".to_string()),
+            Ok(None) => RespValue::Error("Incomplete frame".to_string()),
+            Err(e) => RespValue::Error(format!("Parse error: {}", e)),
+        };
+
+        let response_bytes = Bytes::from(response);
+        sink.send(Message::Binary(response_bytes.to_vec())).await?;
+    }
+
+    Ok(())
+}
+
+/// Periodically samples the keyspace for expired keys instead of relying
+/// solely on lazy expiration in `get`, so TTL-heavy workloads don't leak
+/// memory for keys that are never read again.
+///
+/// Each tick samples up to `SAMPLE_SIZE` keys with a TTL set; if more than
+/// `EXPIRED_RATIO` of the sample was expired, it immediately resamples
+/// before sleeping, keeping the fraction of stale keys bounded.
+async fn run_expiration_sweeper(db_sender: async_channel::Sender<DbOperation>) {
+    const SAMPLE_SIZE: usize = 20;
+    const EXPIRED_RATIO: f64 = 0.25;
+    const SWEEP_INTERVAL: Duration = Duration::from_millis(100);
+
+    loop {
+        loop {
+            let (response_sender, response_receiver) = oneshot::channel();
+            if db_sender
+                .send(DbOperation::SweepExpired(SAMPLE_SIZE, response_sender))
+                .await
+                .is_err()
+            {
+                return;
+            }
+            let Ok((sampled, evicted)) = response_receiver.await else {
+                return;
+            };
+            if sampled == 0 || (evicted as f64 / sampled as f64) <= EXPIRED_RATIO {
+                break;
             }
         }
+        tokio::time::sleep(SWEEP_INTERVAL).await;
     }
 }
-async fn run_database(db_receiver: async_channel::Receiver<DbOperation>) {
+
+/// Converts a stored absolute-millis expiry back into an `Instant`, dropping
+/// the entry (returning `None`) if it already lies in the past.
+fn expiry_from_millis(millis: u64) -> Option<Instant> {
+    let absolute = UNIX_EPOCH + Duration::from_millis(millis);
+    let remaining = absolute.duration_since(SystemTime::now()).ok()?;
+    Some(Instant::now() + remaining)
+}
+
+/// Whether an entry's TTL has elapsed, so lazily-expired keys that the sweeper
+/// hasn't gotten to yet are treated as absent by readers other than `GET`.
+fn is_expired(expiry: Option<Instant>) -> bool {
+    expiry.is_some_and(|e| Instant::now() > e)
+}
+
+/// Builds the list of snapshot entries for the current keyspace, converting
+/// each `Instant`-based expiry to absolute milliseconds since the Unix epoch
+/// so it's still meaningful after a restart.
+fn snapshot_entries(db: &HashMap<String, (Bytes, Option<Instant>)>) -> Vec<SnapshotEntry> {
+    let now_instant = Instant::now();
+    let now_system = SystemTime::now();
+    db.iter()
+        .map(|(key, (value, expiry))| {
+            let expiry_millis = expiry.map(|exp| {
+                let remaining = exp.saturating_duration_since(now_instant);
+                (now_system + remaining)
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64
+            });
+            SnapshotEntry {
+                key: key.clone(),
+                value: value.clone(),
+                expiry_millis,
+            }
+        })
+        .collect()
+}
+
+async fn run_database(
+    db_receiver: async_channel::Receiver<DbOperation>,
+    initial_entries: Vec<SnapshotEntry>,
+) {
     let mut db = HashMap::new();
+    for entry in initial_entries {
+        let expiry = match entry.expiry_millis {
+            Some(millis) => match expiry_from_millis(millis) {
+                Some(expiry) => Some(expiry),
+                None => continue,
+            },
+            None => None,
+        };
+        db.insert(entry.key, (entry.value, expiry));
+    }
 
     while let Ok(operation) = db_receiver.recv().await {
         match operation {
@@ -209,10 +730,167 @@ async fn run_database(db_receiver: async_channel::Receiver<DbOperation>) {
                 let value = db.get(&key).cloned();
                 let _ = response_sender.send(value);
             }
-            DbOperation::Set(key, value, expiry, response_sender) => {
+            DbOperation::Set {
+                key,
+                value,
+                expiry,
+                condition,
+                want_old,
+                response,
+            } => {
+                let existing = db.get(&key).cloned();
+                let is_live = existing
+                    .as_ref()
+                    .map(|(_, exp)| exp.map(|e| Instant::now() <= e).unwrap_or(true))
+                    .unwrap_or(false);
+
+                let condition_met = match condition {
+                    SetCondition::Always => true,
+                    SetCondition::IfNotExists => !is_live,
+                    SetCondition::IfExists => is_live,
+                };
+
+                if !condition_met {
+                    let _ = response.send(SetOutcome::ConditionNotMet);
+                    continue;
+                }
+
+                let old_value = if want_old {
+                    existing.and_then(|(value, exp)| match exp {
+                        Some(e) if Instant::now() > e => None,
+                        _ => Some(value),
+                    })
+                } else {
+                    None
+                };
+
                 db.insert(key, (value, expiry));
+                let _ = response.send(SetOutcome::Set(old_value));
+            }
+            DbOperation::Keys(pattern, response_sender) => {
+                let matches = db
+                    .iter()
+                    .filter(|(key, (_, expiry))| {
+                        !is_expired(*expiry) && glob_match(pattern.as_bytes(), key.as_bytes())
+                    })
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                let _ = response_sender.send(matches);
+            }
+            // The cursor is a plain index into a freshly re-sorted key list on
+            // every call, so inserts/deletes ordering before the cursor between
+            // two SCAN calls can shift the window and skip or repeat keys; this
+            // does not give SCAN's full guarantee that every key present for
+            // the whole iteration is returned at least once.
+            DbOperation::Scan(cursor, pattern, count, response_sender) => {
+                let mut keys: Vec<&String> = db
+                    .iter()
+                    .filter(|(_, (_, expiry))| !is_expired(*expiry))
+                    .map(|(key, _)| key)
+                    .collect();
+                keys.sort();
+                let start = cursor.min(keys.len());
+                let end = (start + count.max(1)).min(keys.len());
+                let page = keys[start..end]
+                    .iter()
+                    .filter(|key| glob_match(pattern.as_bytes(), key.as_bytes()))
+                    .map(|key| (*key).clone())
+                    .collect();
+                let next_cursor = if end >= keys.len() { 0 } else { end };
+                let _ = response_sender.send((next_cursor, page));
+            }
+            DbOperation::DeleteMatching(pattern, response_sender) => {
+                let to_delete: Vec<String> = db
+                    .iter()
+                    .filter(|(key, (_, expiry))| {
+                        !is_expired(*expiry) && glob_match(pattern.as_bytes(), key.as_bytes())
+                    })
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                for key in &to_delete {
+                    db.remove(key);
+                }
+                let _ = response_sender.send(to_delete.len() as u64);
+            }
+            DbOperation::SweepExpired(sample_size, response_sender) => {
+                let keys_with_ttl: Vec<String> = db
+                    .iter()
+                    .filter(|(_, (_, expiry))| expiry.is_some())
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                let mut rng = rand::thread_rng();
+                let sample: Vec<String> = keys_with_ttl
+                    .into_iter()
+                    .choose_multiple(&mut rng, sample_size);
+
+                let now = Instant::now();
+                let mut evicted = 0;
+                for key in &sample {
+                    if let Some((_, Some(exp))) = db.get(key) {
+                        if now > *exp {
+                            db.remove(key);
+                            evicted += 1;
+                        }
+                    }
+                }
+                let _ = response_sender.send((sample.len(), evicted));
+            }
+            DbOperation::Save(path, response_sender) => {
+                let entries = snapshot_entries(&db);
+                let _ = response_sender.send(save_snapshot(&path, &entries));
+            }
+            DbOperation::BgSave(path, response_sender) => {
+                let entries = snapshot_entries(&db);
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = save_snapshot(&path, &entries) {
+                        eprintln!("BGSAVE failed: {}", e);
+                    }
+                });
                 let _ = response_sender.send(());
             }
         }
     }
 }
+
+async fn run_broker(broker_receiver: async_channel::Receiver<BrokerOperation>) {
+    let mut channels: HashMap<String, HashMap<ConnId, mpsc::Sender<RespValue>>> = HashMap::new();
+
+    while let Ok(operation) = broker_receiver.recv().await {
+        match operation {
+            BrokerOperation::Subscribe(channel, conn_id, sender) => {
+                channels.entry(channel).or_default().insert(conn_id, sender);
+            }
+            BrokerOperation::Unsubscribe(channel, conn_id) => {
+                if let Some(subscribers) = channels.get_mut(&channel) {
+                    subscribers.remove(&conn_id);
+                    if subscribers.is_empty() {
+                        channels.remove(&channel);
+                    }
+                }
+            }
+            BrokerOperation::Publish(channel, payload, response_sender) => {
+                let delivered = match channels.get(&channel) {
+                    Some(subscribers) => {
+                        let message = RespValue::Push(vec![
+                            RespValue::BulkString(Some(Bytes::from_static(b"message"))),
+                            RespValue::BulkString(Some(Bytes::from(channel.clone()))),
+                            RespValue::BulkString(Some(payload)),
+                        ]);
+                        let mut delivered = 0u64;
+                        for sender in subscribers.values() {
+                            // A slow subscriber's channel filling up must not
+                            // stall delivery to every other subscriber, so we
+                            // drop the message for that one instead of awaiting.
+                            if sender.try_send(message.clone()).is_ok() {
+                                delivered += 1;
+                            }
+                        }
+                        delivered
+                    }
+                    None => 0,
+                };
+                let _ = response_sender.send(delivered);
+            }
+        }
+    }
+}
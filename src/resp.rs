@@ -1,6 +1,7 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use std::io::{self};
 use thiserror::Error;
+use tokio_util::codec::{Decoder, Encoder};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum RespValue {
@@ -9,6 +10,16 @@ pub enum RespValue {
     Integer(i64),
     BulkString(Option<Bytes>),
     Array(Vec<RespValue>),
+    /// RESP3 out-of-band push message (e.g. a pub/sub delivery), encoded with the `>` prefix.
+    Push(Vec<RespValue>),
+    /// RESP3 map, encoded with the `%` prefix and a count of key/value pairs.
+    Map(Vec<(RespValue, RespValue)>),
+    /// RESP3 set, encoded with the `~` prefix.
+    Set(Vec<RespValue>),
+    /// RESP3 double, encoded with the `,` prefix.
+    Double(f64),
+    /// RESP3 boolean, encoded with the `#` prefix.
+    Boolean(bool),
 }
 
 #[derive(Error, Debug)]
@@ -56,93 +67,242 @@ impl From<RespValue> for Bytes {
                     buf.put(Bytes::from(item));
                 }
             }
+            RespValue::Push(items) => {
+                buf.put_u8(b'>');
+                buf.put(items.len().to_string().as_bytes());
+                buf.put(&b"\r\n"[..]);
+                for item in items {
+                    buf.put(Bytes::from(item));
+                }
+            }
+            RespValue::Map(pairs) => {
+                buf.put_u8(b'%');
+                buf.put(pairs.len().to_string().as_bytes());
+                buf.put(&b"\r\n"[..]);
+                for (key, value) in pairs {
+                    buf.put(Bytes::from(key));
+                    buf.put(Bytes::from(value));
+                }
+            }
+            RespValue::Set(items) => {
+                buf.put_u8(b'~');
+                buf.put(items.len().to_string().as_bytes());
+                buf.put(&b"\r\n"[..]);
+                for item in items {
+                    buf.put(Bytes::from(item));
+                }
+            }
+            RespValue::Double(d) => {
+                buf.put_u8(b',');
+                buf.put(d.to_string().as_bytes());
+                buf.put(&b"\r\n"[..]);
+            }
+            RespValue::Boolean(b) => {
+                buf.put_u8(b'#');
+                buf.put_u8(if b { b't' } else { b'f' });
+                buf.put(&b"\r\n"[..]);
+            }
         }
         buf.freeze()
     }
 }
 
-impl TryFrom<Bytes> for RespValue {
-    type Error = RespError;
-
-    fn try_from(mut bytes: Bytes) -> Result<RespValue, <RespValue as TryFrom<Bytes>>::Error> {
-        if bytes.is_empty() {
-            return Err(RespError::Parse("Empty input".to_string()));
-        }
+/// Finds the offset of the next `\r\n` in `buf` at or after `from`, if any.
+fn find_crlf(buf: &[u8], from: usize) -> Option<usize> {
+    buf.get(from..)?
+        .windows(2)
+        .position(|w| w == b"\r\n")
+        .map(|i| from + i)
+}
 
-        match bytes[0] {
-            b'+' => {
-                bytes.advance(1);
-                let s = String::from_utf8(bytes.split_to(bytes.len() - 2).to_vec())
-                    .map_err(|e| RespError::Parse(e.to_string()))?;
-                Ok(RespValue::SimpleString(s))
+impl RespValue {
+    /// Parses a single `RespValue` out of `buf`, starting at `*pos`.
+    ///
+    /// On success, `*pos` is advanced past the bytes that were consumed. If
+    /// `buf` does not yet contain a complete frame, `*pos` is left untouched
+    /// and `Ok(None)` is returned so the caller can wait for more bytes
+    /// instead of treating a partial frame as a parse error.
+    pub fn parse(buf: &[u8], pos: &mut usize) -> Result<Option<RespValue>, RespError> {
+        let start = *pos;
+        match Self::parse_inner(buf, pos) {
+            Ok(Some(value)) => Ok(Some(value)),
+            Ok(None) => {
+                *pos = start;
+                Ok(None)
             }
-            b'-' => {
-                bytes.advance(1);
-                let s = String::from_utf8(bytes.split_to(bytes.len() - 2).to_vec())
-                    .map_err(|e| RespError::Parse(e.to_string()))?;
-                Ok(RespValue::Error(s))
+            Err(e) => {
+                *pos = start;
+                Err(e)
             }
-            b':' => {
-                bytes.advance(1);
-                let num = String::from_utf8(bytes.split_to(bytes.len() - 2).to_vec())
-                    .map_err(|e| RespError::Parse(e.to_string()))?
-                    .parse::<i64>()
+        }
+    }
+
+    fn parse_inner(buf: &[u8], pos: &mut usize) -> Result<Option<RespValue>, RespError> {
+        let Some(&type_byte) = buf.get(*pos) else {
+            return Ok(None);
+        };
+        *pos += 1;
+
+        match type_byte {
+            b'+' | b'-' | b':' => {
+                let Some(end) = find_crlf(buf, *pos) else {
+                    return Ok(None);
+                };
+                let line = std::str::from_utf8(&buf[*pos..end])
                     .map_err(|e| RespError::Parse(e.to_string()))?;
-                Ok(RespValue::Integer(num))
+                let value = match type_byte {
+                    b'+' => RespValue::SimpleString(line.to_string()),
+                    b'-' => RespValue::Error(line.to_string()),
+                    b':' => RespValue::Integer(
+                        line.parse::<i64>()
+                            .map_err(|e| RespError::Parse(e.to_string()))?,
+                    ),
+                    _ => unreachable!(),
+                };
+                *pos = end + 2;
+                Ok(Some(value))
             }
             b'$' => {
-                bytes.advance(1);
-                let len_end = bytes
-                    .iter()
-                    .position(|&b| b == b'\r')
-                    .ok_or_else(|| RespError::Parse("Invalid bulk string format".to_string()))?;
-                let len = String::from_utf8(bytes.split_to(len_end).to_vec())
+                let Some(end) = find_crlf(buf, *pos) else {
+                    return Ok(None);
+                };
+                let len = std::str::from_utf8(&buf[*pos..end])
                     .map_err(|e| RespError::Parse(e.to_string()))?
                     .parse::<i64>()
                     .map_err(|e| RespError::Parse(e.to_string()))?;
-                bytes.advance(2); // Skip \r\n
+                *pos = end + 2;
+
                 if len == -1 {
-                    Ok(RespValue::BulkString(None))
-                } else {
-                    let data = bytes.split_to(len as usize);
-                    bytes.advance(2); // Skip \r\n
-                    Ok(RespValue::BulkString(Some(data)))
+                    return Ok(Some(RespValue::BulkString(None)));
                 }
+                if len < 0 {
+                    return Err(RespError::Parse(format!(
+                        "invalid bulk string length: {}",
+                        len
+                    )));
+                }
+                let len = len as usize;
+                let data_end = *pos + len;
+                if buf.len() < data_end + 2 {
+                    return Ok(None);
+                }
+                let data = Bytes::copy_from_slice(&buf[*pos..data_end]);
+                *pos = data_end + 2;
+                Ok(Some(RespValue::BulkString(Some(data))))
             }
-            b'*' => {
-                bytes.advance(1);
-
-                let len_end = bytes
-                    .iter()
-                    .position(|&b| b == b'\r')
-                    .ok_or_else(|| RespError::Parse("Invalid array format".to_string()))?;
-
-                let len = String::from_utf8(bytes.split_to(len_end).to_vec())
+            b'*' | b'>' | b'~' => {
+                let Some(end) = find_crlf(buf, *pos) else {
+                    return Ok(None);
+                };
+                let len = std::str::from_utf8(&buf[*pos..end])
                     .map_err(|e| RespError::Parse(e.to_string()))?
                     .parse::<usize>()
                     .map_err(|e| RespError::Parse(e.to_string()))?;
+                *pos = end + 2;
 
-                bytes.advance(2);
-
-                let mut array = Vec::with_capacity(len);
-
+                let mut items = Vec::with_capacity(len);
                 for _ in 0..len {
-                    array.push(RespValue::try_from(bytes.clone())?);
-
-                    bytes.advance(
-                        Bytes::from(
-                            array
-                                .last()
-                                .ok_or_else(|| RespError::Parse("Empty array".to_string()))?
-                                .clone(),
-                        )
-                        .len(),
-                    );
+                    match Self::parse_inner(buf, pos)? {
+                        Some(value) => items.push(value),
+                        None => return Ok(None),
+                    }
                 }
+                Ok(Some(match type_byte {
+                    b'*' => RespValue::Array(items),
+                    b'>' => RespValue::Push(items),
+                    b'~' => RespValue::Set(items),
+                    _ => unreachable!(),
+                }))
+            }
+            b'%' => {
+                let Some(end) = find_crlf(buf, *pos) else {
+                    return Ok(None);
+                };
+                let len = std::str::from_utf8(&buf[*pos..end])
+                    .map_err(|e| RespError::Parse(e.to_string()))?
+                    .parse::<usize>()
+                    .map_err(|e| RespError::Parse(e.to_string()))?;
+                *pos = end + 2;
 
-                Ok(RespValue::Array(array))
+                let mut pairs = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let Some(key) = Self::parse_inner(buf, pos)? else {
+                        return Ok(None);
+                    };
+                    let Some(value) = Self::parse_inner(buf, pos)? else {
+                        return Ok(None);
+                    };
+                    pairs.push((key, value));
+                }
+                Ok(Some(RespValue::Map(pairs)))
+            }
+            b',' => {
+                let Some(end) = find_crlf(buf, *pos) else {
+                    return Ok(None);
+                };
+                let d = std::str::from_utf8(&buf[*pos..end])
+                    .map_err(|e| RespError::Parse(e.to_string()))?
+                    .parse::<f64>()
+                    .map_err(|e| RespError::Parse(e.to_string()))?;
+                *pos = end + 2;
+                Ok(Some(RespValue::Double(d)))
+            }
+            b'#' => {
+                let Some(end) = find_crlf(buf, *pos) else {
+                    return Ok(None);
+                };
+                let b = match &buf[*pos..end] {
+                    b"t" => true,
+                    b"f" => false,
+                    _ => return Err(RespError::Parse("Invalid boolean value".to_string())),
+                };
+                *pos = end + 2;
+                Ok(Some(RespValue::Boolean(b)))
             }
             _ => Err(RespError::Parse("Invalid RESP data type".to_string())),
         }
     }
 }
+
+impl TryFrom<Bytes> for RespValue {
+    type Error = RespError;
+
+    fn try_from(bytes: Bytes) -> Result<RespValue, <RespValue as TryFrom<Bytes>>::Error> {
+        let mut pos = 0;
+        match RespValue::parse(&bytes, &mut pos)? {
+            Some(value) => Ok(value),
+            None => Err(RespError::Parse("Incomplete frame".to_string())),
+        }
+    }
+}
+
+/// A `tokio_util` codec that decodes a byte stream into `RespValue` frames
+/// incrementally, so a frame split across multiple reads is simply held back
+/// until the rest arrives instead of being mis-parsed.
+#[derive(Debug, Default)]
+pub struct RespCodec;
+
+impl Decoder for RespCodec {
+    type Item = RespValue;
+    type Error = RespError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<RespValue>, RespError> {
+        let mut pos = 0;
+        match RespValue::parse(src, &mut pos)? {
+            Some(value) => {
+                src.advance(pos);
+                Ok(Some(value))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Encoder<RespValue> for RespCodec {
+    type Error = RespError;
+
+    fn encode(&mut self, item: RespValue, dst: &mut BytesMut) -> Result<(), RespError> {
+        dst.put(Bytes::from(item));
+        Ok(())
+    }
+}
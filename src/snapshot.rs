@@ -0,0 +1,119 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+/// Identifies the on-disk snapshot format so incompatible files are rejected
+/// instead of silently misparsed.
+const MAGIC: &[u8; 5] = b"RDBRS";
+const VERSION: u8 = 1;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("not a valid snapshot file (bad magic header)")]
+    BadMagic,
+    #[error("unsupported snapshot version {0}")]
+    UnsupportedVersion(u8),
+    #[error("snapshot file is truncated")]
+    Truncated,
+    #[error("snapshot contains invalid UTF-8 key: {0}")]
+    InvalidKey(#[from] std::string::FromUtf8Error),
+}
+
+/// A single stored entry, with its expiry (if any) as absolute milliseconds
+/// since the Unix epoch rather than a process-local `Instant`, since only the
+/// former survives a restart.
+pub struct Entry {
+    pub key: String,
+    pub value: Bytes,
+    pub expiry_millis: Option<u64>,
+}
+
+/// Serializes `entries` to `path` as `[magic][version]` followed by, per
+/// entry, `[key len: u32][key][value len: u32][value][has_expiry: u8][expiry: u64]?`.
+pub fn save(path: &Path, entries: &[Entry]) -> Result<(), SnapshotError> {
+    let mut buf = BytesMut::new();
+    buf.put_slice(MAGIC);
+    buf.put_u8(VERSION);
+
+    for entry in entries {
+        buf.put_u32(entry.key.len() as u32);
+        buf.put_slice(entry.key.as_bytes());
+        buf.put_u32(entry.value.len() as u32);
+        buf.put_slice(&entry.value);
+        match entry.expiry_millis {
+            Some(millis) => {
+                buf.put_u8(1);
+                buf.put_u64(millis);
+            }
+            None => buf.put_u8(0),
+        }
+    }
+
+    std::fs::write(path, &buf)?;
+    Ok(())
+}
+
+/// Parses a snapshot file written by [`save`] back into a list of entries.
+pub fn load(path: &Path) -> Result<Vec<Entry>, SnapshotError> {
+    let data = Bytes::from(std::fs::read(path)?);
+    let mut cursor = &data[..];
+
+    if cursor.len() < MAGIC.len() + 1 || &cursor[..MAGIC.len()] != MAGIC {
+        return Err(SnapshotError::BadMagic);
+    }
+    cursor.advance(MAGIC.len());
+
+    let version = cursor.get_u8();
+    if version != VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let mut entries = Vec::new();
+    while cursor.has_remaining() {
+        if cursor.remaining() < 4 {
+            return Err(SnapshotError::Truncated);
+        }
+        let key_len = cursor.get_u32() as usize;
+        if cursor.remaining() < key_len {
+            return Err(SnapshotError::Truncated);
+        }
+        let key = String::from_utf8(cursor[..key_len].to_vec())?;
+        cursor.advance(key_len);
+
+        if cursor.remaining() < 4 {
+            return Err(SnapshotError::Truncated);
+        }
+        let value_len = cursor.get_u32() as usize;
+        if cursor.remaining() < value_len {
+            return Err(SnapshotError::Truncated);
+        }
+        let value = Bytes::copy_from_slice(&cursor[..value_len]);
+        cursor.advance(value_len);
+
+        if cursor.remaining() < 1 {
+            return Err(SnapshotError::Truncated);
+        }
+        let has_expiry = cursor.get_u8();
+        let expiry_millis = match has_expiry {
+            0 => None,
+            1 => {
+                if cursor.remaining() < 8 {
+                    return Err(SnapshotError::Truncated);
+                }
+                Some(cursor.get_u64())
+            }
+            _ => return Err(SnapshotError::Truncated),
+        };
+
+        entries.push(Entry {
+            key,
+            value,
+            expiry_millis,
+        });
+    }
+
+    Ok(entries)
+}
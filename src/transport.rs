@@ -0,0 +1,128 @@
+use crate::resp::{RespCodec, RespError, RespValue};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, OsRng},
+    ChaCha20Poly1305, Key, KeyInit, Nonce,
+};
+use thiserror::Error;
+use tokio_util::codec::{Decoder, Encoder};
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Error, Debug)]
+pub enum CryptoError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("frame too short to contain a nonce")]
+    FrameTooShort,
+    #[error("decryption failed")]
+    DecryptionFailed,
+    #[error("encryption failed")]
+    EncryptionFailed,
+    #[error(transparent)]
+    Resp(#[from] RespError),
+}
+
+/// Encrypts each RESP frame with ChaCha20-Poly1305 before it hits the wire.
+///
+/// Wire format per frame: `[u32 length][12-byte nonce][ciphertext || 16-byte tag]`,
+/// where `length` covers everything after itself (nonce + ciphertext + tag).
+pub struct EncryptedCodec {
+    cipher: ChaCha20Poly1305,
+}
+
+impl EncryptedCodec {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        }
+    }
+}
+
+impl Decoder for EncryptedCodec {
+    type Item = RespValue;
+    type Error = CryptoError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<RespValue>, CryptoError> {
+        if src.len() < 4 {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes(src[..4].try_into().unwrap()) as usize;
+        if src.len() < 4 + len {
+            return Ok(None);
+        }
+
+        src.advance(4);
+        let frame = src.split_to(len);
+        if frame.len() < NONCE_LEN {
+            return Err(CryptoError::FrameTooShort);
+        }
+        let (nonce_bytes, ciphertext) = frame.split_at(NONCE_LEN);
+        let nonce = Nonce::from_slice(nonce_bytes);
+        let plaintext = self
+            .cipher
+            .decrypt(nonce, ciphertext)
+            .map_err(|_| CryptoError::DecryptionFailed)?;
+
+        let mut pos = 0;
+        let value = RespValue::parse(&plaintext, &mut pos)?
+            .ok_or_else(|| CryptoError::Resp(RespError::Parse("incomplete frame".to_string())))?;
+        Ok(Some(value))
+    }
+}
+
+impl Encoder<RespValue> for EncryptedCodec {
+    type Error = CryptoError;
+
+    fn encode(&mut self, item: RespValue, dst: &mut BytesMut) -> Result<(), CryptoError> {
+        let plaintext = Bytes::from(item);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| CryptoError::EncryptionFailed)?;
+
+        dst.put_u32((NONCE_LEN + ciphertext.len()) as u32);
+        dst.put_slice(&nonce);
+        dst.put_slice(&ciphertext);
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ConnectionError {
+    #[error(transparent)]
+    Resp(#[from] RespError),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+}
+
+/// Picks plaintext RESP framing or ChaCha20-Poly1305-encrypted framing per connection,
+/// so the same listener can serve both depending on whether `--encrypt` is set.
+pub enum ConnectionCodec {
+    Plain(RespCodec),
+    Encrypted(EncryptedCodec),
+}
+
+impl Decoder for ConnectionCodec {
+    type Item = RespValue;
+    type Error = ConnectionError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<RespValue>, ConnectionError> {
+        match self {
+            ConnectionCodec::Plain(codec) => Ok(codec.decode(src)?),
+            ConnectionCodec::Encrypted(codec) => Ok(codec.decode(src)?),
+        }
+    }
+}
+
+impl Encoder<RespValue> for ConnectionCodec {
+    type Error = ConnectionError;
+
+    fn encode(&mut self, item: RespValue, dst: &mut BytesMut) -> Result<(), ConnectionError> {
+        match self {
+            ConnectionCodec::Plain(codec) => Ok(codec.encode(item, dst)?),
+            ConnectionCodec::Encrypted(codec) => Ok(codec.encode(item, dst)?),
+        }
+    }
+}